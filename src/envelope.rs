@@ -0,0 +1,24 @@
+use futures::channel::oneshot;
+
+/// What actually travels over an Actor's mailbox channel.
+///
+/// `try_send`/`send_async` wrap the user's message in `Tell` and forget
+/// about it;
+/// `ask` wraps it in `Ask` along with the `oneshot::Sender` half of
+/// the channel it hands back to the caller, so the driver can deliver
+/// `Actor::handle`'s reply to whoever is awaiting it.
+pub(crate) enum Envelope<M, R> {
+    Tell(M),
+    Ask(M, oneshot::Sender<R>),
+}
+
+impl<M, R> Envelope<M, R> {
+    /// Splits the envelope into the user message and, if this was an
+    /// `ask`, the reply channel to deliver the handler's output to.
+    pub(crate) fn into_parts(self) -> (M, Option<oneshot::Sender<R>>) {
+        match self {
+            Envelope::Tell(msg) => (msg, None),
+            Envelope::Ask(msg, tx) => (msg, Some(tx)),
+        }
+    }
+}
@@ -1,18 +1,27 @@
 use std::{
     any::TypeId,
     error::Error,
-    sync::{
-        Arc, RwLock,
-        atomic::{AtomicBool, Ordering},
-    },
+    future::Future,
+    sync::{Arc, RwLock},
 };
 
 use async_trait::async_trait;
-use futures::channel::mpsc::UnboundedSender;
+use futures::channel::oneshot;
+use tokio_util::sync::CancellationToken;
 
-pub use result::{ActorErr, ActorOk, ActorResult};
+pub use registration::{ActorRegistration, MsgSource};
+pub use result::{ActorErr, ActorOk, ActorResult, HandleResult};
+pub use supervision::{RestartIntensity, Supervisor, SupervisionDirective};
 
+use envelope::Envelope;
+use mailbox::{MailboxSender, MailboxTrySendErr};
+
+mod envelope;
+mod mailbox;
+mod registration;
 mod result;
+mod semaphore;
+pub mod supervision;
 mod utils;
 
 #[cfg(feature = "tokio_impl")]
@@ -25,8 +34,9 @@ pub mod tokio_impl;
 pub struct ActorRef<T: Actor + 'static> {
     id: u64,
     r#type: TypeId, // TODO: Is this still needed?
-    tx: Arc<UnboundedSender<T::Msg>>,
+    tx: Arc<MailboxSender<Envelope<T::Msg, T::Reply>>>,
     state: Arc<RwLock<ActorState>>, // TODO: Should I use AtomicU8 instead?
+    token: CancellationToken,
 }
 
 /// State of the Actor.
@@ -34,6 +44,10 @@ pub struct ActorRef<T: Actor + 'static> {
 pub enum ActorState {
     /// The Actor is healthy, running, and listening.
     Healthy,
+    /// The Actor was asked to stop gracefully and is no longer
+    /// accepting new messages, but is still draining whatever was
+    /// already queued before it becomes `Stopped`.
+    Stopping,
     /// The Actor is stopped, and did so without error.
     Stopped,
     /// The Actor is stopped, but did so as the result of
@@ -41,10 +55,22 @@ pub enum ActorState {
     Crashed,
 }
 
+/// Returned by `ActorRef::try_send` when a message couldn't be
+/// enqueued immediately.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SendError {
+    /// The actor's bounded mailbox is at capacity. Retry later, or
+    /// use `ActorRef::send_async` to wait for room instead.
+    Full,
+    /// The actor isn't `Healthy`, so nothing is listening.
+    Disconnected(ActorState),
+}
+
 /// The Actor Trait.
 #[async_trait]
 pub trait Actor: Send + Sync {
     type Msg: Send + Sync;
+    type Reply: Send + Sync;
     type Err: Error + Send + Sync;
 
     /// This is ran synchronously after an Actor is given
@@ -52,21 +78,41 @@ pub trait Actor: Send + Sync {
     async fn start(&mut self) -> ActorResult<Self::Err> { Ok(ActorOk::Success) }
 
     /// The handle that the ActorSystem invokes when a message is
-    /// sent to the Actor. This will get wrapped in an ActorFuture
-    async fn handle(&mut self, msg: Self::Msg) -> ActorResult<Self::Err>;
+    /// sent to the Actor. The driver's dequeue loop awaits it, one
+    /// message at a time.
+    ///
+    /// The returned reply is delivered to the caller if the message
+    /// came in through `ActorRef::ask`, and discarded if it came in
+    /// through `ActorRef::try_send`/`send_async`.
+    async fn handle(&mut self, msg: Self::Msg) -> HandleResult<Self::Reply, Self::Err>;
 
     /// This is ran synchronously on request through the ActorSystem.
     /// It will be blocked by any current message handles.
     async fn stop(&mut self) -> ActorResult<Self::Err> { Ok(ActorOk::GracefulEnd) }
 }
 
+/// Bundles everything `register` needs to supervise an Actor: who
+/// decides what to do when it crashes or reports an error, how to
+/// build a fresh instance for a `Restart`, and how many restarts to
+/// allow before escalating to `Stop`.
+pub struct Supervision<T: Actor> {
+    pub supervisor: Arc<dyn Supervisor<T::Err>>,
+    pub factory: Box<dyn Fn() -> T + Send + Sync>,
+    pub intensity: RestartIntensity,
+}
+
 /// The internal driver for the ActorSystem. This defines threading
 /// and storage implementations.
 #[async_trait]
 pub trait ActorSystemDriver {
-    async fn register<T>(&self, mut actor: T) -> (ActorRef<T>, Option<T::Err>) where
+    /// `mailbox_capacity` of `None` gives the actor an unbounded
+    /// mailbox; `Some(cap)` bounds it, trading latency under load for
+    /// backpressure against the mailbox growing without limit.
+    /// `sources` are extra message streams merged into the dequeue
+    /// loop alongside the mailbox; see `ActorRegistration::with_source`.
+    async fn register<T>(&self, mut actor: T, supervision: Option<Supervision<T>>, mailbox_capacity: Option<usize>, sources: Vec<MsgSource<T::Msg>>) -> (ActorRef<T>, Option<T::Err>) where
         T: Actor + 'static;
-    fn is_running(&self) -> Arc<AtomicBool>;
+    fn is_running(&self) -> bool;
     fn stop(&self);
 }
 
@@ -74,24 +120,100 @@ pub trait ActorSystemDriver {
 /// ActorSystemDriver. Implementation-agnostic details will be added
 /// here.
 pub struct ActorSystem<T: ActorSystemDriver + Sized> {
-    is_running: Arc<AtomicBool>,
-    inner: Arc<T>,
+    pub(crate) inner: Arc<T>,
 }
 
 impl<T: Actor + 'static> ActorRef<T> {
-    /// Send a message to the Actor to handle
-    pub fn send(&self, msg: T::Msg) -> Result<(), ActorState> {
-        let g = self.state.read()
-            .expect("poisoned actor state guard, report to dev");
+    /// Snapshots the Actor's current `ActorState`.
+    fn read_state(&self) -> ActorState {
+        self.state.read()
+            .expect("poisoned actor state guard, report to dev")
+            .clone()
+    }
+
+    /// Enqueue a message for the Actor to handle without waiting for
+    /// mailbox room. Fails immediately if the Actor isn't `Healthy`
+    /// or, for a bounded mailbox, if it's currently full; use
+    /// `send_async` to wait for room instead.
+    pub fn try_send(&self, msg: T::Msg) -> Result<(), SendError> {
+        let state = self.read_state();
+
+        if state != ActorState::Healthy {
+            return Err(SendError::Disconnected(state));
+        }
+
+        self.tx.try_send(Envelope::Tell(msg)).map_err(|e| match e {
+            MailboxTrySendErr::Full => SendError::Full,
+            // The Actor stopped between the check above and this
+            // send (e.g. a concurrent `stop()` closed the mailbox);
+            // report it the same as if the check had seen it.
+            MailboxTrySendErr::Disconnected => SendError::Disconnected(self.read_state()),
+        })
+    }
+
+    /// Enqueue a message for the Actor to handle, awaiting room in a
+    /// bounded mailbox rather than failing immediately when it's full.
+    /// Unbounded mailboxes never wait, so this resolves as soon as
+    /// `try_send` would have.
+    pub fn send_async(&self, msg: T::Msg) -> impl Future<Output = Result<(), ActorState>> {
+        let state = self.read_state();
+        let healthy = if state != ActorState::Healthy { Err(state) } else { Ok(()) };
+
+        let tx = self.tx.clone();
+        let state = self.state.clone();
+
+        async move {
+            healthy?;
+
+            // A send parked awaiting mailbox room can still be sitting
+            // there when the Actor is asked to stop and closes the
+            // mailbox out from under it; that's a live race, not a
+            // bug, so report whatever state the Actor ended up in
+            // instead of asserting it can't happen.
+            tx.send_async(Envelope::Tell(msg)).await
+                .map_err(|_| state.read().expect("poisoned actor state guard, report to dev").clone())?;
 
-        if *g != ActorState::Healthy {
-            return Err(g.clone());
+            Ok(())
         }
+    }
+
+    /// Send a message to the Actor and get back the reply from its
+    /// `Actor::handle`. Awaits mailbox room the same way `send_async`
+    /// does, then resolves to `Err` if the Actor wasn't `Healthy` to
+    /// begin with, or if it crashes/stops before replying.
+    pub fn ask(&self, msg: T::Msg) -> impl Future<Output = Result<T::Reply, ActorState>> {
+        let state = self.read_state();
+        let healthy = if state != ActorState::Healthy { Err(state) } else { Ok(()) };
+
+        let tx = self.tx.clone();
+        let state = self.state.clone();
+
+        async move {
+            healthy?;
+
+            let (reply_tx, reply_rx) = oneshot::channel();
 
-        self.tx.unbounded_send(msg)
-            .expect("healthy actor has disconnected channel, report to dev");
+            // Same race as `send_async`: the mailbox can close while
+            // this send is parked waiting for room.
+            tx.send_async(Envelope::Ask(msg, reply_tx)).await
+                .map_err(|_| state.read().expect("poisoned actor state guard, report to dev").clone())?;
 
-        Ok(())
+            // A dropped reply sender doesn't always mean the Actor
+            // crashed: a supervised Actor that errored and got
+            // `Resume`d or successfully `Restart`ed also drops it,
+            // while staying (or becoming) perfectly `Healthy`. Report
+            // whichever state it actually ended up in.
+            reply_rx.await
+                .map_err(|_| state.read().expect("poisoned actor state guard, report to dev").clone())
+        }
+    }
+
+    /// Asks the Actor to stop gracefully: it stops accepting new
+    /// messages, drains whatever is already queued through `handle`,
+    /// runs `Actor::stop`, and becomes `Stopped`. Unlike `ActorSystem::stop`,
+    /// this affects only this one actor.
+    pub fn stop(&self) {
+        self.token.cancel();
     }
 }
 
@@ -106,22 +228,20 @@ impl<T: Actor + 'static> Eq for ActorRef<T> {}
 impl<T: ActorSystemDriver> ActorSystem<T> {
     pub fn new(driver: T) -> Self {
         ActorSystem {
-            is_running: driver.is_running(),
             inner: Arc::new(driver)
         }
     }
 
-    /// Stop the ActorSystem. Actors will stop processing
-    /// messages and the system will be dead. This should
-    /// only be used when the entire system is to be
-    /// dropped
+    /// Stop the ActorSystem. Every registered Actor is asked to stop
+    /// gracefully, the same way `ActorRef::stop` would, draining its
+    /// queued messages before becoming `Stopped`.
     pub fn stop(&self) {
         self.inner.stop()
     }
 
-    /// Atomically checks if the ActorSystem is running
+    /// Checks whether the ActorSystem has been asked to stop
     pub fn is_running(&self) -> bool {
-        self.is_running.load(Ordering::Relaxed)
+        self.inner.is_running()
     }
 
     /// Registers an Actor to the ActorSystem. This
@@ -131,7 +251,35 @@ impl<T: ActorSystemDriver> ActorSystem<T> {
     /// Returns the ActorRef handle and an Option with
     /// any error returned by Actor::start
     pub async fn register<A: Actor + 'static>(&self, actor: A) -> (ActorRef<A>, Option<A::Err>) {
-        self.inner.register(actor).await
+        self.inner.register(actor, None, None, Vec::new()).await
+    }
+
+    /// Registers an Actor under supervision. If it returns
+    /// `ActorErr::Crashing` or `ActorErr::Reporting` from `handle`,
+    /// `supervision.supervisor` is consulted for a `SupervisionDirective`
+    /// before the ActorSystem decides the actor's fate on its behalf.
+    pub async fn register_supervised<A: Actor + 'static>(&self, actor: A, supervision: Supervision<A>) -> (ActorRef<A>, Option<A::Err>) {
+        self.inner.register(actor, Some(supervision), None, Vec::new()).await
+    }
+
+    /// Registers an Actor with a mailbox bounded to `capacity`
+    /// messages, so a fast sender backs off (`send_async`) or fails
+    /// fast (`try_send`) instead of growing the queue without limit.
+    pub async fn register_bounded<A: Actor + 'static>(&self, actor: A, capacity: usize) -> (ActorRef<A>, Option<A::Err>) {
+        self.inner.register(actor, None, Some(capacity), Vec::new()).await
+    }
+
+    /// Combines `register_supervised` and `register_bounded`.
+    pub async fn register_supervised_bounded<A: Actor + 'static>(&self, actor: A, supervision: Supervision<A>, capacity: usize) -> (ActorRef<A>, Option<A::Err>) {
+        self.inner.register(actor, Some(supervision), Some(capacity), Vec::new()).await
+    }
+
+    /// Starts building a registration for `actor` that needs more than
+    /// the combinations the `register*` methods above cover directly,
+    /// e.g. extra message sources merged into its dequeue loop. See
+    /// `ActorRegistration`.
+    pub fn register_builder<A: Actor + 'static>(&self, actor: A) -> ActorRegistration<'_, T, A> {
+        ActorRegistration::new(self, actor)
     }
 }
 
@@ -142,6 +290,7 @@ impl<T: Actor + 'static> Clone for ActorRef<T> {
             r#type: self.r#type,
             tx: self.tx.clone(),
             state: self.state.clone(),
+            token: self.token.clone(),
         }
     }
 }
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::sync::Arc;
+
+use futures::channel::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use futures::StreamExt;
+
+use crate::semaphore::Semaphore;
+
+/// What actually backs an Actor's mailbox channel. The channel itself
+/// is always the unbounded `futures::mpsc` one; a bounded mailbox
+/// additionally gates admission through a `Semaphore` sized to its
+/// capacity, rather than relying on `mpsc::channel`'s own bounded
+/// variant.
+///
+/// `mpsc::Sender`'s notion of "full" is entangled with how many
+/// `Sender` clones are currently live (each gets its own guaranteed
+/// slot), and a bounded `Sender` can only be polled for readiness
+/// through a specific, shared instance — so a fresh clone per send
+/// never observes backpressure, while a single shared instance behind
+/// a lock serializes every concurrent sender. A plain counting
+/// semaphore sidesteps both: any number of callers can cheaply clone
+/// the (always-unbounded) sender and race to acquire a permit, with
+/// admission bounded purely by permits available, not by sender
+/// identity.
+pub(crate) struct MailboxSender<M> {
+    tx: UnboundedSender<M>,
+    limit: Option<Arc<Semaphore>>,
+}
+
+impl<M> fmt::Debug for MailboxSender<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tx.fmt(f)
+    }
+}
+
+pub(crate) struct MailboxReceiver<M> {
+    rx: UnboundedReceiver<M>,
+    limit: Option<Arc<Semaphore>>,
+}
+
+/// Why `MailboxSender::try_send` couldn't enqueue a message right now.
+pub(crate) enum MailboxTrySendErr {
+    /// The bounded mailbox is at capacity.
+    Full,
+    /// The receiving half is gone.
+    Disconnected,
+}
+
+pub(crate) fn channel<M>(capacity: Option<usize>) -> (MailboxSender<M>, MailboxReceiver<M>) {
+    let (tx, rx) = mpsc::unbounded();
+    let limit = capacity.map(|cap| Arc::new(Semaphore::new(cap)));
+
+    (MailboxSender { tx, limit: limit.clone() }, MailboxReceiver { rx, limit })
+}
+
+impl<M> MailboxSender<M> {
+    /// Enqueues `msg` without waiting for room, mirroring the
+    /// behavior an unbounded mailbox always had.
+    pub(crate) fn try_send(&self, msg: M) -> Result<(), MailboxTrySendErr> {
+        if let Some(limit) = &self.limit {
+            if !limit.try_acquire() {
+                return Err(MailboxTrySendErr::Full);
+            }
+        }
+
+        self.tx.unbounded_send(msg).map_err(|_| {
+            // Nobody's left to release this permit back to; the
+            // mailbox is gone either way.
+            MailboxTrySendErr::Disconnected
+        })
+    }
+
+    /// Enqueues `msg`, waiting for room in a bounded mailbox rather
+    /// than failing immediately. Errs only if the actor is gone.
+    pub(crate) async fn send_async(&self, msg: M) -> Result<(), ()> {
+        if let Some(limit) = &self.limit {
+            limit.acquire().await;
+        }
+
+        self.tx.unbounded_send(msg).map_err(|_| ())
+    }
+}
+
+impl<M> MailboxReceiver<M> {
+    pub(crate) async fn recv(&mut self) -> Option<M> {
+        let msg = self.rx.next().await;
+
+        // A dequeued message is no longer occupying mailbox space,
+        // regardless of whether the caller that sent it is even still
+        // around to have cared about the backpressure.
+        if msg.is_some() {
+            if let Some(limit) = &self.limit {
+                limit.release();
+            }
+        }
+
+        msg
+    }
+
+    /// Closes the sending side: every outstanding `MailboxSender`
+    /// starts rejecting new messages as `Disconnected`, while whatever
+    /// is already buffered is still returned by `recv` until drained.
+    pub(crate) fn close(&mut self) {
+        self.rx.close();
+    }
+}
@@ -0,0 +1,65 @@
+use std::pin::Pin;
+
+use futures::Stream;
+
+use crate::{Actor, ActorRef, ActorSystem, ActorSystemDriver, Supervision};
+
+/// A stream of pre-built messages merged into an Actor's dequeue loop
+/// alongside its mailbox, e.g. a timer tick or a broadcast subscription
+/// forwarded straight into `Actor::handle` without a feeder task.
+pub type MsgSource<M> = Pin<Box<dyn Stream<Item = M> + Send>>;
+
+/// Builds up a `register` call one option at a time, so attaching a
+/// `Supervision`, a bounded mailbox, and extra message sources doesn't
+/// require a combinatorial method for every combination. Get one from
+/// `ActorSystem::register_builder`, and finish with `spawn`.
+pub struct ActorRegistration<'a, T: ActorSystemDriver, A: Actor> {
+    system: &'a ActorSystem<T>,
+    actor: A,
+    supervision: Option<Supervision<A>>,
+    mailbox_capacity: Option<usize>,
+    sources: Vec<MsgSource<A::Msg>>,
+}
+
+impl<'a, T: ActorSystemDriver, A: Actor + 'static> ActorRegistration<'a, T, A> {
+    pub(crate) fn new(system: &'a ActorSystem<T>, actor: A) -> Self {
+        Self {
+            system,
+            actor,
+            supervision: None,
+            mailbox_capacity: None,
+            sources: Vec::new(),
+        }
+    }
+
+    /// Supervise the Actor the way `ActorSystem::register_supervised` does.
+    pub fn supervised(mut self, supervision: Supervision<A>) -> Self {
+        self.supervision = Some(supervision);
+        self
+    }
+
+    /// Bound the Actor's mailbox the way `ActorSystem::register_bounded` does.
+    pub fn bounded(mut self, capacity: usize) -> Self {
+        self.mailbox_capacity = Some(capacity);
+        self
+    }
+
+    /// Merges an additional stream of messages into the Actor's dequeue
+    /// loop. Items are delivered to the Actor's single `Actor::handle`
+    /// the same as a `try_send` would (no reply is collected) — there's
+    /// no per-source dispatch, so a source whose items aren't already
+    /// `A::Msg` needs an upstream `.map()` into a variant `Actor::handle`
+    /// recognizes. Can be called more than once to attach several
+    /// sources.
+    pub fn with_source(mut self, source: impl Stream<Item = A::Msg> + Send + 'static) -> Self {
+        self.sources.push(Box::pin(source));
+        self
+    }
+
+    /// Finishes the registration, spawning the Actor as configured.
+    pub async fn spawn(self) -> (ActorRef<A>, Option<A::Err>) {
+        self.system.inner
+            .register(self.actor, self.supervision, self.mailbox_capacity, self.sources)
+            .await
+    }
+}
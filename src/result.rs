@@ -2,6 +2,12 @@ use std::error::Error;
 
 pub type ActorResult<Err> = Result<ActorOk, ActorErr<Err>>;
 
+/// The result of `Actor::handle`. Unlike `ActorResult`, it carries the
+/// actor's reply alongside the lifecycle signal, so that `ActorRef::ask`
+/// has something to deliver through its `oneshot` channel. Callers that
+/// only `try_send`/`send_async` simply discard the reply.
+pub type HandleResult<R, Err> = Result<(ActorOk, R), ActorErr<Err>>;
+
 #[repr(u8)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ActorOk {
@@ -16,10 +22,9 @@ pub enum ActorOk {
 pub enum ActorErr<T> where
     T: Error + Send + Sync
 {
-    // TODO: Enable when adding monitors
-//    /// The actor is reporting an error, but the actor should
-//    /// be treated as though the error has been recovered.
-//    Reporting(T),
+    /// The actor is reporting an error, but the actor should
+    /// be treated as though the error has been recovered.
+    Reporting(T),
     /// The actor has encountered an error that means the actor
     /// is no longer in a functioning state and should be killed.
     Crashing(T),
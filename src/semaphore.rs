@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll, Waker};
+
+/// A minimal async-aware counting semaphore, used to gate admission
+/// into a bounded mailbox independently of whatever capacity
+/// bookkeeping the underlying channel does internally (see
+/// `mailbox::MailboxSender`'s doc comment for why that distinction
+/// matters).
+pub(crate) struct Semaphore {
+    state: Mutex<State>,
+}
+
+struct State {
+    available: usize,
+    waiters: VecDeque<Waker>,
+}
+
+impl Semaphore {
+    pub(crate) fn new(permits: usize) -> Self {
+        Self {
+            state: Mutex::new(State { available: permits, waiters: VecDeque::new() }),
+        }
+    }
+
+    /// Acquires a permit without waiting, or reports there isn't one.
+    pub(crate) fn try_acquire(&self) -> bool {
+        let mut state = self.state.lock().expect("poisoned semaphore, report to dev");
+
+        if state.available == 0 {
+            return false;
+        }
+
+        state.available -= 1;
+        true
+    }
+
+    /// Acquires a permit, waiting for one to be `release`d if none are
+    /// immediately available.
+    pub(crate) fn acquire(&self) -> Acquire<'_> {
+        Acquire { semaphore: self }
+    }
+
+    /// Returns a permit, waking the longest-waiting `acquire` if any
+    /// is parked.
+    pub(crate) fn release(&self) {
+        let mut state = self.state.lock().expect("poisoned semaphore, report to dev");
+
+        state.available += 1;
+
+        if let Some(waker) = state.waiters.pop_front() {
+            waker.wake();
+        }
+    }
+}
+
+pub(crate) struct Acquire<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Future for Acquire<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.semaphore.state.lock().expect("poisoned semaphore, report to dev");
+
+        if state.available > 0 {
+            state.available -= 1;
+            return Poll::Ready(());
+        }
+
+        state.waiters.push_back(cx.waker().clone());
+        Poll::Pending
+    }
+}
@@ -0,0 +1,69 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::ActorErr;
+
+/// What a `Supervisor` decides to do about an Actor that crashed or
+/// reported an error, Erlang/OTP-style.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SupervisionDirective {
+    /// Tear the actor down and spin up a fresh instance from its
+    /// factory, as long as it's still within its `RestartIntensity`.
+    Restart,
+    /// Leave the actor `Crashed`.
+    Stop,
+    /// Treat the error as recovered and leave the actor `Healthy`.
+    Resume,
+}
+
+/// Implemented by types that want to react when a supervised Actor
+/// returns `ActorErr::Crashing` or `ActorErr::Reporting` from `handle`.
+#[async_trait]
+pub trait Supervisor<Err>: Send + Sync where Err: Error + Send + Sync {
+    async fn on_error(&self, err: &ActorErr<Err>) -> SupervisionDirective;
+}
+
+/// Caps how many times a `Supervisor` may `Restart` an Actor within a
+/// rolling time window, so a crash loop escalates to `Stop` rather
+/// than spinning forever.
+#[derive(Clone, Debug)]
+pub struct RestartIntensity {
+    pub max_restarts: usize,
+    pub within: Duration,
+}
+
+impl Default for RestartIntensity {
+    fn default() -> Self {
+        Self { max_restarts: 3, within: Duration::from_secs(5) }
+    }
+}
+
+/// Tracks recent restarts for a single supervised Actor to enforce
+/// its `RestartIntensity`.
+pub(crate) struct RestartTracker {
+    intensity: RestartIntensity,
+    restarts: Vec<Instant>,
+}
+
+impl RestartTracker {
+    pub(crate) fn new(intensity: RestartIntensity) -> Self {
+        Self { intensity, restarts: Vec::new() }
+    }
+
+    /// Records a restart attempt and reports whether it's still
+    /// within the allowed intensity, dropping restarts that have
+    /// aged out of the window first.
+    pub(crate) fn allow_restart(&mut self) -> bool {
+        let now = Instant::now();
+        self.restarts.retain(|t| now.duration_since(*t) <= self.intensity.within);
+
+        if self.restarts.len() >= self.intensity.max_restarts {
+            false
+        } else {
+            self.restarts.push(now);
+            true
+        }
+    }
+}
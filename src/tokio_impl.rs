@@ -1,19 +1,18 @@
 use std::{
     any::TypeId,
-    sync::{
-        Arc, atomic::{AtomicBool, Ordering},
-        RwLock,
-    },
+    panic::AssertUnwindSafe,
+    sync::{Arc, RwLock},
 };
 
 use async_trait::async_trait;
-use futures::channel::mpsc::{
-    unbounded,
-    UnboundedReceiver,
-};
-use futures::StreamExt;
+use futures::{FutureExt, StreamExt};
+use futures::stream::{self, SelectAll};
+use tokio_util::sync::CancellationToken;
 
-use crate::{Actor, ActorErr, ActorOk, ActorRef, ActorState, ActorSystemDriver};
+use crate::{Actor, ActorErr, ActorOk, ActorRef, ActorState, ActorSystemDriver, MsgSource, Supervision};
+use crate::envelope::Envelope;
+use crate::mailbox::{self, MailboxReceiver};
+use crate::supervision::{RestartTracker, SupervisionDirective};
 use crate::utils::SnowflakeProducer;
 
 /// ActorSystemDriver implementation that uses the user's
@@ -21,97 +20,522 @@ use crate::utils::SnowflakeProducer;
 #[derive(Default)]
 pub struct TokioActorDriver {
     snowflakes: SnowflakeProducer,
-    is_running: Arc<AtomicBool>,
+    shutdown: CancellationToken,
+}
+
+impl TokioActorDriver {
+    /// Builds a driver whose actor IDs identify themselves as
+    /// `worker_id`, so multiple driver instances (e.g. across
+    /// processes) produce collision-free IDs. The default driver
+    /// (`TokioActorDriver::default`) always uses worker id `0`, which
+    /// is only safe for a single instance.
+    pub fn with_worker(worker_id: u64) -> Self {
+        Self {
+            snowflakes: SnowflakeProducer::with_worker(worker_id),
+            shutdown: CancellationToken::new(),
+        }
+    }
 }
 
 #[async_trait]
 impl ActorSystemDriver for TokioActorDriver {
-    async fn register<T>(&self, mut actor: T) -> (ActorRef<T>, Option<T::Err>) where
+    async fn register<T>(&self, mut actor: T, supervision: Option<Supervision<T>>, mailbox_capacity: Option<usize>, sources: Vec<MsgSource<T::Msg>>) -> (ActorRef<T>, Option<T::Err>) where
         T: Actor + 'static
     {
         let id = self.snowflakes.produce();
 
-        let (tx, rx) = unbounded::<T::Msg>();
+        let (tx, rx) = mailbox::channel::<Envelope<T::Msg, T::Reply>>(mailbox_capacity);
 
-        let (state, err) = match actor.start().await {
-            Ok(ok) => {
+        let (state, err) = match AssertUnwindSafe(actor.start()).catch_unwind().await {
+            Ok(Ok(ok)) => {
                 match ok {
                     ActorOk::Success => (ActorState::Healthy, None),
                     ActorOk::GracefulEnd => (ActorState::Stopped, None),
                 }
             }
-            Err(err) => {
+            Ok(Err(err)) => {
                 match err {
-                    // TODO: Enable when adding Monitors
-                    // ActorErr::Reporting(e) => (ActorState::Healthy, Some(e)),
+                    ActorErr::Reporting(e) => (ActorState::Healthy, Some(e)),
                     ActorErr::Crashing(e) => (ActorState::Crashed, Some(e)),
                 }
             }
+            // Actor::start panicked before producing a typed error;
+            // treat it as stillborn rather than propagating the panic
+            // into the caller awaiting register().
+            Err(_panic) => (ActorState::Crashed, None),
         };
 
         let state = Arc::new(RwLock::new(state));
 
+        // A child of the system-wide shutdown token: cancelling it
+        // alone (`ActorRef::stop`) only affects this actor, while
+        // cancelling the parent (`ActorSystem::stop`) cascades to
+        // every actor's token at once.
+        let token = self.shutdown.child_token();
+
         let actor_ref = ActorRef {
             id,
             r#type: TypeId::of::<T>(),
             tx: Arc::new(tx),
             state: state.clone(),
+            token: token.clone(),
         };
 
         let state_g = state.read().unwrap();
 
         if *state_g == ActorState::Healthy {
-            let running = self.is_running.clone();
-
             drop(state_g);
 
-            tokio::spawn(dequeue_for_actor(actor, state, rx, running));
+            tokio::spawn(dequeue_for_actor(actor, state, rx, token, supervision, stream::select_all(sources)));
         }
 
         (actor_ref, err)
     }
 
-    fn is_running(&self) -> Arc<AtomicBool> {
-        self.is_running.clone()
+    fn is_running(&self) -> bool {
+        !self.shutdown.is_cancelled()
     }
 
     fn stop(&self) {
-        self.is_running.swap(false, Ordering::Acquire);
+        self.shutdown.cancel();
     }
 }
 
 async fn dequeue_for_actor<T: Actor + 'static>(mut actor: T, state: Arc<RwLock<ActorState>>,
-                                     mut rx: UnboundedReceiver<T::Msg>, is_running: Arc<AtomicBool>) {
-    while is_running.load(Ordering::Relaxed) {
-        if let Some(msg) = rx.next().await {
-            {
-                let state_g = state.read()
-                    .expect("poisoned actor_state, report to dev");
+                                     mut rx: MailboxReceiver<Envelope<T::Msg, T::Reply>>, token: CancellationToken,
+                                     supervision: Option<Supervision<T>>, mut sources: SelectAll<MsgSource<T::Msg>>) {
+    let mut restart_tracker = supervision.as_ref()
+        .map(|s| RestartTracker::new(s.intensity.clone()));
 
-                if *state_g != ActorState::Healthy {
-                    break;
+    // Once `token` fires we stop selecting against it (it's already
+    // cancelled, so re-selecting would just spin) and fall through to
+    // draining whatever's left in the mailbox instead of dropping it.
+    let mut draining = false;
+    let mut drained_gracefully = false;
+
+    loop {
+        let envelope = if draining {
+            rx.recv().await
+        } else {
+            tokio::select! {
+                biased;
+                _ = token.cancelled() => {
+                    draining = true;
+
+                    // Stop accepting new sends immediately: every
+                    // outstanding `ActorRef` now gets `Disconnected`
+                    // from the closed mailbox, and the early healthy
+                    // check in `try_send`/`send_async`/`ask` also
+                    // rejects on sight rather than waiting for the
+                    // drain to finish naturally.
+                    rx.close();
+
+                    let mut state_g = state.write()
+                        .expect("poisoned actor_state, report to dev");
+
+                    *state_g = ActorState::Stopping;
+
+                    drop(state_g);
+
+                    continue;
                 }
+                envelope = rx.recv() => envelope,
+                // Extra sources carry no reply channel, so they're
+                // treated the same as a `try_send`'d message. Once
+                // we're draining for shutdown, only the mailbox
+                // (already-accepted work) keeps being drained.
+                Some(msg) = sources.next() => Some(Envelope::Tell(msg)),
             }
+        };
+
+        let Some(envelope) = envelope else {
+            drained_gracefully = draining;
+            break;
+        };
 
-            let handle_result = actor.handle(msg).await;
+        if !draining {
+            let state_g = state.read()
+                .expect("poisoned actor_state, report to dev");
 
-            {
+            if *state_g != ActorState::Healthy {
+                break;
+            }
+        }
+
+        let (msg, reply_tx) = envelope.into_parts();
+
+        // A panicking actor must not leave behind a zombie `Healthy`
+        // reference: without catch_unwind, the spawned task would
+        // simply die here and `state` would never be updated, so
+        // `ActorRef::try_send`/`ask` would keep "succeeding" into a
+        // mailbox nobody is draining.
+        let handle_result = match AssertUnwindSafe(actor.handle(msg)).catch_unwind().await {
+            Ok(result) => result,
+            Err(_panic) => {
                 let mut state_g = state.write()
                     .expect("poisoned actor_state, report to dev");
 
-                match handle_result {
-                    Ok(ok) => {
-                        if ok == ActorOk::GracefulEnd {
-                            *state_g = ActorState::Stopped;
-                        }
+                *state_g = ActorState::Crashed;
+                break;
+            }
+        };
+
+        match handle_result {
+            Ok((ok, reply)) => {
+                {
+                    let mut state_g = state.write()
+                        .expect("poisoned actor_state, report to dev");
+
+                    if ok == ActorOk::GracefulEnd {
+                        *state_g = ActorState::Stopped;
                     }
-                    Err(err) => {
-                        if let ActorErr::Crashing(_e) = err {
+                }
+
+                // If the caller used `try_send`/`send_async` instead
+                // of `ask`, there's no one listening; a failed send
+                // here just means they've already dropped the receiver.
+                if let Some(reply_tx) = reply_tx {
+                    let _ = reply_tx.send(reply);
+                }
+
+                if ok == ActorOk::GracefulEnd {
+                    break;
+                }
+            }
+            Err(err) => {
+                let directive = match supervision.as_ref() {
+                    Some(supervision) => supervision.supervisor.on_error(&err).await,
+                    // No supervisor attached: fall back to the
+                    // pre-supervision behavior those variants
+                    // already documented.
+                    None => match &err {
+                        ActorErr::Reporting(_) => SupervisionDirective::Resume,
+                        ActorErr::Crashing(_) => SupervisionDirective::Stop,
+                    },
+                };
+
+                match directive {
+                    SupervisionDirective::Resume => {}
+                    SupervisionDirective::Stop => {
+                        let mut state_g = state.write()
+                            .expect("poisoned actor_state, report to dev");
+
+                        *state_g = ActorState::Crashed;
+                        break;
+                    }
+                    SupervisionDirective::Restart => {
+                        let Some(supervision) = supervision.as_ref() else { break; };
+
+                        let within_intensity = restart_tracker.as_mut()
+                            .map(RestartTracker::allow_restart)
+                            .unwrap_or(false);
+
+                        if !within_intensity {
+                            let mut state_g = state.write()
+                                .expect("poisoned actor_state, report to dev");
+
                             *state_g = ActorState::Crashed;
+                            break;
+                        }
+
+                        let mut fresh = (supervision.factory)();
+                        let restarted_ok = matches!(
+                            AssertUnwindSafe(fresh.start()).catch_unwind().await,
+                            Ok(Ok(_))
+                        );
+                        actor = fresh;
+
+                        let mut state_g = state.write()
+                            .expect("poisoned actor_state, report to dev");
+
+                        *state_g = if restarted_ok {
+                            ActorState::Healthy
+                        } else {
+                            ActorState::Crashed
+                        };
+
+                        if !restarted_ok {
+                            break;
                         }
                     }
                 }
             }
-        } else { break; }
+        }
+    }
+
+    // Only an actor that drained its mailbox after being asked to
+    // stop gets the graceful `Actor::stop` treatment; one that
+    // crashed, restarted out, or ended itself via `GracefulEnd`
+    // already has a final state and shouldn't be stopped again.
+    if drained_gracefully {
+        let _ = AssertUnwindSafe(actor.stop()).catch_unwind().await;
+
+        let mut state_g = state.write()
+            .expect("poisoned actor_state, report to dev");
+
+        *state_g = ActorState::Stopped;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::fmt;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::{
+        Actor, ActorErr, ActorOk, ActorState, ActorSystem, HandleResult, RestartIntensity,
+        SendError, Supervision, Supervisor, SupervisionDirective,
+    };
+
+    use super::TokioActorDriver;
+
+    #[derive(Debug)]
+    struct FlakyErr;
+
+    impl fmt::Display for FlakyErr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "flaky error")
+        }
+    }
+
+    impl std::error::Error for FlakyErr {}
+
+    /// An Actor that reports whether it's the instance originally
+    /// registered, so a restart swapping it for a fresh one (built by a
+    /// `Supervision::factory`) is observable from outside.
+    struct Flaky {
+        original: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Actor for Flaky {
+        type Msg = bool;
+        type Reply = bool;
+        type Err = FlakyErr;
+
+        async fn handle(&mut self, should_crash: bool) -> HandleResult<bool, FlakyErr> {
+            if should_crash {
+                Err(ActorErr::Crashing(FlakyErr))
+            } else {
+                Ok((ActorOk::Success, self.original))
+            }
+        }
+    }
+
+    struct AlwaysRestart;
+
+    #[async_trait::async_trait]
+    impl Supervisor<FlakyErr> for AlwaysRestart {
+        async fn on_error(&self, _err: &ActorErr<FlakyErr>) -> SupervisionDirective {
+            SupervisionDirective::Restart
+        }
+    }
+
+    struct AlwaysResume;
+
+    #[async_trait::async_trait]
+    impl Supervisor<FlakyErr> for AlwaysResume {
+        async fn on_error(&self, _err: &ActorErr<FlakyErr>) -> SupervisionDirective {
+            SupervisionDirective::Resume
+        }
+    }
+
+    #[tokio::test]
+    async fn restart_directive_replaces_the_actor_and_respects_intensity() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+
+        let supervision = Supervision {
+            supervisor: Arc::new(AlwaysRestart),
+            factory: Box::new(|| Flaky { original: false }),
+            intensity: RestartIntensity { max_restarts: 1, within: Duration::from_secs(60) },
+        };
+
+        let (actor_ref, _) = system.register_supervised(Flaky { original: true }, supervision).await;
+
+        assert_eq!(actor_ref.ask(false).await, Ok(true));
+
+        // 1st crash: still within intensity, so the actor is Restarted
+        // with a fresh instance from the factory. The dequeue loop
+        // processes messages strictly in order, so this `ask` can't
+        // resolve until the restart ahead of it has finished.
+        actor_ref.try_send(true).expect("mailbox accepts the crashing message");
+        assert_eq!(actor_ref.ask(false).await, Ok(false));
+
+        // 2nd crash: exceeds `max_restarts`, so the supervisor's
+        // `Restart` escalates to `Stop` instead.
+        actor_ref.try_send(true).expect("mailbox accepts the crashing message");
+        assert_eq!(actor_ref.ask(false).await, Err(ActorState::Crashed));
+    }
+
+    #[tokio::test]
+    async fn resume_directive_keeps_the_same_actor_healthy() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+
+        let supervision = Supervision {
+            supervisor: Arc::new(AlwaysResume),
+            factory: Box::new(|| Flaky { original: false }),
+            intensity: RestartIntensity::default(),
+        };
+
+        let (actor_ref, _) = system.register_supervised(Flaky { original: true }, supervision).await;
+
+        // The crashing `ask` itself never gets a reply (its reply
+        // sender is dropped without use), but `Resume` leaves the
+        // Actor `Healthy` rather than killing it — the caller should
+        // see that, not a hardcoded `Crashed`.
+        assert_eq!(actor_ref.ask(true).await, Err(ActorState::Healthy));
+
+        // And the very next `ask` goes through to the same,
+        // unrestarted instance.
+        assert_eq!(actor_ref.ask(false).await, Ok(true));
+    }
+
+    struct NoOp;
+
+    #[async_trait::async_trait]
+    impl Actor for NoOp {
+        type Msg = ();
+        type Reply = ();
+        type Err = Infallible;
+
+        async fn handle(&mut self, _msg: ()) -> HandleResult<(), Infallible> {
+            Ok((ActorOk::Success, ()))
+        }
+    }
+
+    struct Boom;
+
+    #[async_trait::async_trait]
+    impl Actor for Boom {
+        type Msg = ();
+        type Reply = ();
+        type Err = Infallible;
+
+        async fn handle(&mut self, _msg: ()) -> HandleResult<(), Infallible> {
+            panic!("boom");
+        }
+    }
+
+    #[tokio::test]
+    async fn handle_panic_crashes_the_actor_without_killing_the_task() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+        let (actor_ref, _) = system.register(Boom).await;
+
+        actor_ref.try_send(()).expect("mailbox accepts the panicking message");
+
+        // `catch_unwind` around `handle` should turn the panic into a
+        // `Crashed` actor, not a silently dead task: if the task died
+        // instead, `try_send` would keep reporting `Healthy` forever
+        // into a mailbox nobody is draining.
+        for _ in 0..100 {
+            if matches!(actor_ref.try_send(()), Err(SendError::Disconnected(ActorState::Crashed))) {
+                return;
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        panic!("a panicking handle() never crashed the actor");
+    }
+
+    struct StillbornOnStart;
+
+    #[async_trait::async_trait]
+    impl Actor for StillbornOnStart {
+        type Msg = ();
+        type Reply = ();
+        type Err = Infallible;
+
+        async fn start(&mut self) -> crate::ActorResult<Infallible> {
+            panic!("boom");
+        }
+
+        async fn handle(&mut self, _msg: ()) -> HandleResult<(), Infallible> {
+            Ok((ActorOk::Success, ()))
+        }
+    }
+
+    #[tokio::test]
+    async fn start_panic_leaves_the_actor_stillborn() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+        let (actor_ref, err) = system.register(StillbornOnStart).await;
+
+        assert!(err.is_none());
+        assert!(matches!(actor_ref.try_send(()), Err(SendError::Disconnected(ActorState::Crashed))));
+    }
+
+    #[derive(Default)]
+    struct Counter {
+        total: i32,
+    }
+
+    #[async_trait::async_trait]
+    impl Actor for Counter {
+        type Msg = i32;
+        type Reply = i32;
+        type Err = Infallible;
+
+        async fn handle(&mut self, msg: i32) -> HandleResult<i32, Infallible> {
+            self.total += msg;
+            Ok((ActorOk::Success, self.total))
+        }
+    }
+
+    #[tokio::test]
+    async fn extra_sources_are_merged_into_the_same_handle() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+
+        let (actor_ref, _) = system.register_builder(Counter::default())
+            .with_source(futures::stream::iter([1, 2, 3]))
+            .spawn()
+            .await;
+
+        // The source's items race the dequeue loop's own polling, so
+        // asking (which adds 0, leaving the total unchanged) may
+        // observe a partial sum before they've all landed.
+        for _ in 0..100 {
+            if actor_ref.ask(0).await == Ok(6) {
+                return;
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        panic!("messages from an attached source never reached handle()");
+    }
+
+    #[tokio::test]
+    async fn bounded_mailbox_rejects_once_full() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+        let (actor_ref, _) = system.register_bounded(NoOp, 1).await;
+
+        // Capacity 1 admits exactly one message before the dequeue
+        // loop even needs to run; a second must observe `Full`.
+        actor_ref.try_send(()).expect("first send should fit");
+
+        assert!(matches!(actor_ref.try_send(()), Err(SendError::Full)));
+    }
+
+    #[tokio::test]
+    async fn stop_rejects_new_sends_promptly() {
+        let system = ActorSystem::<TokioActorDriver>::default();
+        let (actor_ref, _) = system.register(NoOp).await;
+
+        actor_ref.stop();
+
+        // `stop` is observed by the dequeue loop on its next poll, not
+        // synchronously with this call, so give it a bounded number of
+        // scheduler turns to close the mailbox before giving up.
+        for _ in 0..100 {
+            if matches!(actor_ref.try_send(()), Err(SendError::Disconnected(_))) {
+                return;
+            }
+
+            tokio::task::yield_now().await;
+        }
+
+        panic!("stop() never closed the mailbox to new sends");
     }
 }